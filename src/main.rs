@@ -1,6 +1,8 @@
-extern crate crossbeam;
 extern crate num;
 extern crate gif;
+extern crate rand;
+extern crate rayon;
+extern crate image;
 
 use std::io::{self, Write};
 use std::str::FromStr;
@@ -8,31 +10,158 @@ use std::fs::File;
 use std::borrow::Cow;
 use num::Complex;
 use gif::SetParameter;
+use rand::Rng;
+use rayon::prelude::*;
+use image::png::PNGEncoder;
+use image::ColorType;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
-    if args.len() != 5 {
-        writeln!(
-            std::io::stderr(),
-            r#"
-                Usage: {0} NAME NO_OF_FRAMES ZOOM_SPEED POINT
-                Example: {0} mandel 50 0.1 -0.77568377,0.13646737
-            "#,
-            args[0]
-        ).unwrap();
-
-        std::process::exit(1);
+    match args.get(1).map(|s| s.as_str()) {
+        Some("zoom") => run_zoom(&args),
+        Some("buddhabrot") => run_buddhabrot(&args),
+        Some("still") => run_still(&args),
+        _ => print_usage_and_exit(&args[0]),
+    }
+}
+
+fn print_usage_and_exit(program: &str) -> ! {
+    writeln!(
+        std::io::stderr(),
+        r#"
+            Usage: {0} zoom NAME NO_OF_FRAMES ZOOM_SPEED POINT FRACTAL_KIND PALETTE
+            Example: {0} zoom mandel 50 0.1 -0.77568377,0.13646737 mandelbrot hsv
+
+            Usage: {0} buddhabrot NAME SAMPLES LIMIT PALETTE
+            Example: {0} buddhabrot nebula 5000000 255 fire
+
+            Usage: {0} still OUTPUT WIDTH HEIGHT POINT FRACTAL_KIND PALETTE
+            Example: {0} still wallpaper.png 3840 2160 -0.77568377,0.13646737 mandelbrot hsv
+        "#,
+        program
+    ).unwrap();
+
+    std::process::exit(1);
+}
+
+/// Render an animated zoom into the set, as a GIF, using the existing frame/viewport machinery.
+fn run_zoom(args: &[String]) {
+    if args.len() != 8 {
+        print_usage_and_exit(&args[0]);
     }
 
     let size = (750, 750);
-    let name = &args[1];
-    let number_of_frames = usize::from_str(&args[2]).unwrap();
-    let zoom_speed = f64::from_str(&args[3]).unwrap();
-    let central_point = parse_complex(&args[4]).expect("error while parsing upper left point");
+    let name = &args[2];
+    let number_of_frames = usize::from_str(&args[3]).unwrap();
+    let zoom_speed = f64::from_str(&args[4]).unwrap();
+    let central_point = parse_complex(&args[5]).expect("error while parsing upper left point");
+    let fractal_kind = FractalKind::from_str(&args[6]).expect("error while parsing fractal kind");
+    let palette = Palette::from_str(&args[7]).expect("error while parsing palette");
+    let (upper_left, lower_right) = fetch_upper_left_and_lower_right_coordinates_based_on_central_point(central_point);
+
+    generate_gif(name, number_of_frames, zoom_speed, size, upper_left, lower_right, fractal_kind, palette);
+}
+
+/// Render a single Buddhabrot still image, as a one-frame GIF.
+fn run_buddhabrot(args: &[String]) {
+    if args.len() != 6 {
+        print_usage_and_exit(&args[0]);
+    }
+
+    let size = (750, 750);
+    let name = &args[2];
+    let samples = usize::from_str(&args[3]).unwrap();
+    let limit = u32::from_str(&args[4]).unwrap();
+    let palette = Palette::from_str(&args[5]).expect("error while parsing palette");
+    let upper_left = Complex { re: -2.0, im: 2.0 };
+    let lower_right = Complex { re: 2.0, im: -2.0 };
+
+    generate_buddhabrot_gif(name, size, upper_left, lower_right, samples, limit, palette);
+}
+
+/// Render a single high-resolution still frame, as a PNG or a one-frame GIF depending on
+/// `output`'s extension. Reuses `generate_frame`/`render` for the pixel data; the PNG path skips
+/// GIF encoding, but its colors still come from the same 256-entry color table.
+fn run_still(args: &[String]) {
+    if args.len() != 8 {
+        print_usage_and_exit(&args[0]);
+    }
+
+    let output = &args[2];
+    let width = usize::from_str(&args[3]).unwrap();
+    let height = usize::from_str(&args[4]).unwrap();
+    let central_point = parse_complex(&args[5]).expect("error while parsing central point");
+    let fractal_kind = FractalKind::from_str(&args[6]).expect("error while parsing fractal kind");
+    let palette = Palette::from_str(&args[7]).expect("error while parsing palette");
+    let size = (width, height);
     let (upper_left, lower_right) = fetch_upper_left_and_lower_right_coordinates_based_on_central_point(central_point);
 
-    generate_gif(name, number_of_frames, zoom_speed, size, upper_left, lower_right);
+    let pixels = generate_frame(size, upper_left, lower_right, fractal_kind);
+
+    if output.ends_with(".png") {
+        write_png(output, size, &pixels, palette);
+    } else {
+        write_single_frame_gif(output, size, &pixels, palette);
+    }
+}
+
+/// The iteration rule used to decide whether a point escapes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FractalKind {
+    Mandelbrot,
+    Multibrot3,
+    BurningShip,
+}
+
+impl FromStr for FractalKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mandelbrot" => Ok(FractalKind::Mandelbrot),
+            "multibrot3" => Ok(FractalKind::Multibrot3),
+            "burningship" => Ok(FractalKind::BurningShip),
+            _ => Err(format!("unknown fractal kind: {}", s)),
+        }
+    }
+}
+
+#[test]
+fn test_fractal_kind_from_str() {
+    assert_eq!(FractalKind::from_str("mandelbrot"), Ok(FractalKind::Mandelbrot));
+    assert_eq!(FractalKind::from_str("Multibrot3"), Ok(FractalKind::Multibrot3));
+    assert_eq!(FractalKind::from_str("burningship"), Ok(FractalKind::BurningShip));
+    assert!(FractalKind::from_str("nope").is_err());
+}
+
+/// A GIF color table gradient, selectable from the CLI.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Palette {
+    Grayscale,
+    Fire,
+    Hsv,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "grayscale" => Ok(Palette::Grayscale),
+            "fire" => Ok(Palette::Fire),
+            "hsv" => Ok(Palette::Hsv),
+            _ => Err(format!("unknown palette: {}", s)),
+        }
+    }
+}
+
+#[test]
+fn test_palette_from_str() {
+    assert_eq!(Palette::from_str("grayscale"), Ok(Palette::Grayscale));
+    assert_eq!(Palette::from_str("Fire"), Ok(Palette::Fire));
+    assert_eq!(Palette::from_str("hsv"), Ok(Palette::Hsv));
+    assert!(Palette::from_str("nope").is_err());
 }
 
 /// Fetch touple with `(upper_left, lower_right)` points, based on the `central_point`.
@@ -53,6 +182,8 @@ fn generate_gif(
     size: (usize, usize),
     upper_left: Complex<f64>,
     lower_right: Complex<f64>,
+    fractal_kind: FractalKind,
+    palette: Palette,
     ) -> () {
 
     let mut frame_states = Vec::new();
@@ -61,7 +192,7 @@ fn generate_gif(
     let mut zoomed_lower_right = lower_right;
 
     for index in 0..number_of_frames {
-        let frame_state = generate_frame(size, zoomed_upper_left, zoomed_lower_right);
+        let frame_state = generate_frame(size, zoomed_upper_left, zoomed_lower_right, fractal_kind);
         frame_states.push(frame_state);
 
         let width = (zoomed_upper_left.re - zoomed_lower_right.re).abs();
@@ -85,7 +216,7 @@ fn generate_gif(
 
     let filename_with_extension = format!("{}.gif", filename);
     let mut image = File::create(filename_with_extension).unwrap();
-    let mut encoder = gif::Encoder::new(&mut image, size.0 as u16, size.1 as u16, &fetch_color_map()).unwrap();
+    let mut encoder = gif::Encoder::new(&mut image, size.0 as u16, size.1 as u16, &fetch_color_map(palette)).unwrap();
     encoder.set(gif::Repeat::Infinite).unwrap();
 
     for i in 0..frame_states.len() * 2 {
@@ -102,50 +233,264 @@ fn generate_gif(
     }
 }
 
-fn fetch_color_map() -> [u8; 256 * 3] {
+/// Render a Buddhabrot image: instead of coloring each pixel by its own escape time, accumulate
+/// the trajectories of escaping points and write the result as a single-frame GIF.
+fn generate_buddhabrot_gif(
+    filename: &str,
+    size: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    samples: usize,
+    limit: u32,
+    palette: Palette,
+    ) -> () {
+
+    let accumulator = accumulate_buddhabrot_trajectories(size, upper_left, lower_right, samples, limit);
+    let pixels = normalize_buddhabrot_accumulator(&accumulator);
+
+    write_single_frame_gif(&format!("{}.gif", filename), size, &pixels, palette);
+}
+
+/// Write `pixels` out as a looping, single-frame GIF using `palette`'s color table.
+fn write_single_frame_gif(filename: &str, size: (usize, usize), pixels: &[u8], palette: Palette) {
+    let mut image = File::create(filename).unwrap();
+    let mut encoder = gif::Encoder::new(&mut image, size.0 as u16, size.1 as u16, &fetch_color_map(palette)).unwrap();
+    encoder.set(gif::Repeat::Infinite).unwrap();
+
+    let mut frame = gif::Frame::default();
+    frame.width = size.0 as u16;
+    frame.height = size.1 as u16;
+    frame.buffer = Cow::Borrowed(pixels);
+    encoder.write_frame(&frame).unwrap();
+}
+
+/// Write `pixels` out as a PNG, expanding each palette-indexed byte into the actual color via
+/// `palette`'s color table. The grayscale palette is written as 8-bit grayscale; every other
+/// palette is written as 8-bit RGB, since its gradient isn't literally grayscale.
+fn write_png(filename: &str, size: (usize, usize), pixels: &[u8], palette: Palette) {
+    let color_map = fetch_color_map(palette);
+    let file = File::create(filename).unwrap();
+    let encoder = PNGEncoder::new(file);
+
+    match palette {
+        Palette::Grayscale => {
+            let gray: Vec<u8> = pixels.iter().map(|&p| color_map[p as usize * 3]).collect();
+            encoder.encode(&gray, size.0 as u32, size.1 as u32, ColorType::Gray(8)).unwrap();
+        }
+        _ => {
+            let mut rgb = Vec::with_capacity(pixels.len() * 3);
+            for &p in pixels {
+                let index = p as usize * 3;
+                rgb.extend_from_slice(&color_map[index..index + 3]);
+            }
+            encoder.encode(&rgb, size.0 as u32, size.1 as u32, ColorType::RGB(8)).unwrap();
+        }
+    }
+}
+
+/// Sample `samples` random points `c` in the `upper_left`/`lower_right` viewport, and for every
+/// one that escapes within `limit` iterations of `z = z*z + c`, replay its trajectory and bump
+/// the accumulator at every pixel an intermediate `z` lands on. Points that never escape, and
+/// intermediate `z` values that fall outside the viewport, contribute nothing.
+fn accumulate_buddhabrot_trajectories(
+    size: (usize, usize),
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    samples: usize,
+    limit: u32,
+    ) -> Vec<u32> {
+    let mut accumulator = vec![0u32; size.0 * size.1];
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..samples {
+        let c = Complex {
+            re: rng.gen_range(upper_left.re, lower_right.re),
+            im: rng.gen_range(lower_right.im, upper_left.im),
+        };
+
+        let mut trajectory = Vec::new();
+        let mut z = Complex { re: 0.0, im: 0.0 };
+        let mut escaped = false;
+
+        for _ in 0..limit {
+            z = z * z + c;
+            trajectory.push(z);
+
+            if z.norm_sqr() > 4.0 {
+                escaped = true;
+                break;
+            }
+        }
+
+        if !escaped {
+            continue;
+        }
+
+        for point in trajectory {
+            if let Some((column, row)) = point_to_pixel(size, point, upper_left, lower_right) {
+                accumulator[column + row * size.0] += 1;
+            }
+        }
+    }
+
+    accumulator
+}
+
+/// Inverse of `pixel_to_point`: map a point on the complex plane back to the pixel it falls in,
+/// or `None` if it lies outside the `upper_left`/`lower_right` viewport.
+fn point_to_pixel(
+    size: (usize, usize),
+    point: Complex<f64>,
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    ) -> Option<(usize, usize)> {
+    if point.re < upper_left.re || point.re >= lower_right.re {
+        return None;
+    }
+    if point.im > upper_left.im || point.im <= lower_right.im {
+        return None;
+    }
+
+    let complex_width = lower_right.re - upper_left.re;
+    let complex_height = upper_left.im - lower_right.im;
+
+    let column = ((point.re - upper_left.re) / complex_width * size.0 as f64) as usize;
+    let row = ((upper_left.im - point.im) / complex_height * size.1 as f64) as usize;
+
+    Some((column, row))
+}
+
+#[test]
+fn test_point_to_pixel() {
+    let upper_left = Complex { re: -1.0, im: 1.0 };
+    let lower_right = Complex { re: 1.0, im: -1.0 };
+
+    assert_eq!(
+        point_to_pixel((100, 100), Complex { re: -0.5, im: -0.5 }, upper_left, lower_right),
+        Some((25, 75))
+    );
+    assert_eq!(
+        point_to_pixel((100, 100), Complex { re: 5.0, im: 5.0 }, upper_left, lower_right),
+        None
+    );
+}
+
+/// Normalize a raw accumulator buffer into `0..=254` grayscale-style bytes on a log scale, so a
+/// handful of extremely hot pixels don't wash out the rest of the image. Index 255 is left for
+/// the interior color reserved by `fetch_color_map`.
+fn normalize_buddhabrot_accumulator(accumulator: &[u32]) -> Vec<u8> {
+    let max = accumulator.iter().cloned().max().unwrap_or(0);
+
+    if max == 0 {
+        return vec![0; accumulator.len()];
+    }
+
+    let scale = (INTERIOR_INDEX - 1) as f64 / (1.0 + max as f64).ln();
+
+    accumulator
+        .iter()
+        .map(|&count| ((1.0 + count as f64).ln() * scale).round() as u8)
+        .collect()
+}
+
+/// Pixel value written for points that never escape (see `render`), kept as a distinct entry
+/// in every palette rather than being part of the gradient.
+const INTERIOR_INDEX: usize = 255;
+const INTERIOR_COLOR: [u8; 3] = [0, 0, 0];
+
+fn fetch_color_map(palette: Palette) -> [u8; 256 * 3] {
     let mut color_map: [u8; 256*3] = [0; 256*3];
 
     for i in 0..256 {
-        let rgb = [255 - i, 255 - i, 255 - i];
+        let rgb = if i == INTERIOR_INDEX {
+            INTERIOR_COLOR
+        } else {
+            match palette {
+                Palette::Grayscale => grayscale_color(i),
+                Palette::Fire => fire_color(i),
+                Palette::Hsv => hsv_to_rgb(360.0 * i as f64 / 256.0, 1.0, 1.0),
+            }
+        };
 
         for j in 0..rgb.len() {
-            color_map[i * rgb.len() + j] = rgb[j] as u8;
+            color_map[i * rgb.len() + j] = rgb[j];
         }
     }
 
     color_map
 }
 
+fn grayscale_color(i: usize) -> [u8; 3] {
+    let v = 255 - i as u8;
+    [v, v, v]
+}
+
+/// Interpolate black -> red -> yellow -> white across the palette index range.
+fn fire_color(i: usize) -> [u8; 3] {
+    let t = i as f64 / (INTERIOR_INDEX - 1) as f64;
+
+    if t < 1.0 / 3.0 {
+        [(255.0 * t * 3.0).round() as u8, 0, 0]
+    } else if t < 2.0 / 3.0 {
+        [255, (255.0 * (t - 1.0 / 3.0) * 3.0).round() as u8, 0]
+    } else {
+        [255, 255, (255.0 * (t - 2.0 / 3.0) * 3.0).round() as u8]
+    }
+}
+
+/// Convert an HSV color (`h` in degrees, `s` and `v` in `[0, 1]`) to 8-bit RGB.
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> [u8; 3] {
+    let c = v * s;
+    let h_prime = h / 60.0;
+    let x = c * (1.0 - (h_prime % 2.0 - 1.0).abs());
+    let (r1, g1, b1) = if h_prime < 1.0 {
+        (c, x, 0.0)
+    } else if h_prime < 2.0 {
+        (x, c, 0.0)
+    } else if h_prime < 3.0 {
+        (0.0, c, x)
+    } else if h_prime < 4.0 {
+        (0.0, x, c)
+    } else if h_prime < 5.0 {
+        (x, 0.0, c)
+    } else {
+        (c, 0.0, x)
+    };
+    let m = v - c;
+
+    [
+        ((r1 + m) * 255.0).round() as u8,
+        ((g1 + m) * 255.0).round() as u8,
+        ((b1 + m) * 255.0).round() as u8,
+    ]
+}
+
+#[test]
+fn test_hsv_to_rgb() {
+    assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), [255, 0, 0]);
+    assert_eq!(hsv_to_rgb(120.0, 1.0, 1.0), [0, 255, 0]);
+    assert_eq!(hsv_to_rgb(240.0, 1.0, 1.0), [0, 0, 255]);
+}
+
 /// Generate single frame, represented as `Vec<u8>` where `u8` values represent how many iterations there were needed for given point, to leave the set.
+///
+/// Each row is rendered independently on rayon's work-stealing pool, which sizes itself to the
+/// number of available cores instead of a fixed thread count.
 fn generate_frame(
     size: (usize, usize),
     upper_left: Complex<f64>,
     lower_right: Complex<f64>,
+    fractal_kind: FractalKind,
     ) -> Vec<u8> {
     let mut pixels = vec![0; size.0 * size.1];
 
-    let threads = 4;
-
-    let rows_per_band = size.1 / threads + 1;
-
-    {
-        let bands: Vec<&mut [u8]> = pixels.chunks_mut(rows_per_band * size.0).collect();
-        crossbeam::scope(|spawner| {
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / size.0;
+    pixels.par_chunks_mut(size.0).enumerate().for_each(|(row, band)| {
+        let band_size = (size.0, 1);
+        let band_upper_left = pixel_to_point(size, (0, row), upper_left, lower_right);
+        let band_lower_right = pixel_to_point(size, (size.0, row + 1), upper_left, lower_right);
 
-                let band_size = (size.0, height);
-                let band_upper_left = pixel_to_point(size, (0, top), upper_left, lower_right);
-                let band_lower_right =
-                    pixel_to_point(size, (size.0, top + height), upper_left, lower_right);
-
-                spawner.spawn(move || {
-                    render(band, band_size, band_upper_left, band_lower_right);
-                });
-            }
-        });
-    }
+        render(band, band_size, band_upper_left, band_lower_right, fractal_kind);
+    });
 
     return pixels;
 }
@@ -162,20 +507,42 @@ fn render(
     size: (usize, usize),
     upper_left: Complex<f64>,
     lower_right: Complex<f64>,
+    fractal_kind: FractalKind,
 ) {
     assert!(pixels.len() == size.0 * size.1);
 
+    let limit = 255;
+
     for row in 0..size.1 {
         for column in 0..size.0 {
             let point = pixel_to_point(size, (column, row), upper_left, lower_right);
-            pixels[column + row * size.0] = match escape_time(point, 255) {
-                None => 255,
-                Some(count) => count as u8,
+            pixels[column + row * size.0] = match escape_time(point, limit, fractal_kind) {
+                None => INTERIOR_INDEX as u8,
+                Some((count, z)) => smoothed_iteration_count(count, z).round() as u8,
             };
         }
     }
 }
 
+/// Turn a raw escape-time iteration count into a continuous iteration count.
+///
+/// Interpolating between the iteration at which `z` escaped and the next one, using how far past
+/// the escape radius `z` actually landed, removes the banding that a plain integer count produces.
+/// `mu` is clamped to `[0, INTERIOR_INDEX - 1]`, not just guarding against `z.norm()` landing so
+/// close to 1.0 that `ln(ln(z.norm()))` blows up, but also so a pixel that genuinely escaped can
+/// never land on `INTERIOR_INDEX`, the color table entry reserved for non-escaping points.
+fn smoothed_iteration_count(count: u32, z: Complex<f64>) -> f64 {
+    let mu = count as f64 + 1.0 - (z.norm().ln().ln()) / 2.0f64.ln();
+    mu.max(0.0).min((INTERIOR_INDEX - 1) as f64)
+}
+
+#[test]
+fn test_smoothed_iteration_count_never_reaches_interior_index() {
+    let just_past_escape_radius = Complex { re: 2.001, im: 0.0 };
+    let mu = smoothed_iteration_count(254, just_past_escape_radius);
+    assert!(mu < INTERIOR_INDEX as f64);
+}
+
 /// Given the row and column of pixel on the image,
 /// return the corresponding point on the complex plane.
 ///
@@ -247,17 +614,25 @@ fn test_parse_pair() {
     assert_eq!(parse_pair::<i32>("10x20", 'x'), Some((10, 20)));
 }
 
-/// Try to determine if `c` is in the Mandelbrot set, using at most `limit` iterations.
+/// Try to determine if `c` is in the set described by `fractal_kind`, using at most `limit` iterations.
 ///
-/// If `c` is NOT a member return `Some(i)`, where `i` is the number of iterations it took `c` to leave the set.
+/// If `c` is NOT a member return `Some((i, z))`, where `i` is the number of iterations it took `c`
+/// to leave the set and `z` is the value it escaped with (used for smooth coloring).
 /// If `c` seems to be a member (stays withing set in `limit` iterations), return `None`.
-fn escape_time(c: Complex<f64>, limit: u32) -> Option<u32> {
+fn escape_time(c: Complex<f64>, limit: u32, fractal_kind: FractalKind) -> Option<(u32, Complex<f64>)> {
     let mut z = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
-        z = z * z + c;
+        z = match fractal_kind {
+            FractalKind::Mandelbrot => z * z + c,
+            FractalKind::Multibrot3 => z * z * z + c,
+            FractalKind::BurningShip => {
+                let folded = Complex { re: z.re.abs(), im: z.im.abs() };
+                folded * folded + c
+            }
+        };
 
         if z.norm_sqr() > 4.0 {
-            return Some(i);
+            return Some((i, z));
         }
     }
 